@@ -1,11 +1,19 @@
 //! Camera type with support for perspective and orthographic projections.
 
-use amethyst_core::cgmath::{Deg, Matrix4, Ortho, PerspectiveFov};
-use specs::{Component, HashMapStorage, Entity};
+use std::marker::PhantomData;
+
+use amethyst_core::cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Matrix4, Ortho, PerspectiveFov, Point2, Point3, SquareMatrix,
+    Vector2, Vector3, Vector4,
+};
+use specs::{
+    Component, Entity, Fetch, HashMapStorage, Join, NullStorage, ReadStorage, System,
+    WriteStorage,
+};
+
+use ScreenDimensions;
 
 /// The projection mode of a `Camera`.
-///
-/// TODO: Remove and integrate with `Camera`.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum Projection {
     /// An [orthographic projection][op].
@@ -20,15 +28,15 @@ pub enum Projection {
 
 impl Projection {
     /// Creates an orthographic projection with the given left, right, top, and
-    /// bottom plane distances.
-    pub fn orthographic(l: f32, r: f32, t: f32, b: f32) -> Projection {
+    /// bottom plane distances, and the given near and far plane distances.
+    pub fn orthographic(l: f32, r: f32, t: f32, b: f32, near: f32, far: f32) -> Projection {
         Projection::Orthographic(Ortho {
             left: l,
             right: r,
             top: t,
             bottom: b,
-            near: 0.1,
-            far: 2000.0,
+            near,
+            far,
         })
     }
 
@@ -44,18 +52,36 @@ impl Projection {
     }
 }
 
-impl From<Projection> for Matrix4<f32> {
-    fn from(proj: Projection) -> Self {
-        match proj {
+/// Trait implemented by a `Camera`'s projection, so the projection matrix can be recomputed
+/// in place whenever the viewport it is rendered into changes size.
+pub trait CameraProjection {
+    /// Computes the projection matrix represented by this projection.
+    fn get_projection_matrix(&self) -> Matrix4<f32>;
+    /// Recomputes the projection's parameters for a viewport of the given size.
+    fn update(&mut self, width: f32, height: f32);
+}
+
+impl CameraProjection for Projection {
+    fn get_projection_matrix(&self) -> Matrix4<f32> {
+        match *self {
             Projection::Orthographic(ortho) => ortho.into(),
             Projection::Perspective(perspective) => perspective.into(),
         }
     }
-}
 
-impl From<Projection> for Camera {
-    fn from(proj: Projection) -> Self {
-        Self { proj: proj.into() }
+    fn update(&mut self, width: f32, height: f32) {
+        match *self {
+            Projection::Orthographic(ref mut ortho) => {
+                let half_height = (ortho.top - ortho.bottom) / 2.0;
+                let half_width = half_height * (width / height);
+                let center = (ortho.left + ortho.right) / 2.0;
+                ortho.left = center - half_width;
+                ortho.right = center + half_width;
+            }
+            Projection::Perspective(ref mut perspective) => {
+                perspective.aspect = width / height;
+            }
+        }
     }
 }
 
@@ -63,7 +89,18 @@ impl From<Projection> for Camera {
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Camera {
     /// Graphical projection of the camera.
-    pub proj: Matrix4<f32>,
+    pub proj: Projection,
+    /// Cached projection matrix. `proj` is a public field so this is not updated automatically
+    /// when it is edited directly; `CameraProjectionSystem` recomputes it from `proj` every
+    /// frame, so any direct edit (e.g. a manual FOV/zoom change) is picked up on the next tick.
+    pub matrix: Matrix4<f32>,
+}
+
+impl From<Projection> for Camera {
+    fn from(proj: Projection) -> Self {
+        let matrix = proj.get_projection_matrix();
+        Self { proj, matrix }
+    }
 }
 
 impl Camera {
@@ -72,8 +109,17 @@ impl Camera {
     /// Will use an orthographic projection with lower left corner being (-1., -1.) and
     /// upper right (1., 1.).
     /// View transformation will be multiplicative identity.
-    pub fn standard_2d() -> Self {
-        Self::from(Projection::orthographic(-1., 1., 1., -1.))
+    ///
+    /// Uses a symmetric near/far range centered on the origin, rather than the `near >= 0` range
+    /// a 3D camera needs, so sprites can be layered in front of and behind `z = 0` like CSS.
+    ///
+    /// Also returns a `Camera2d` marker, so the camera can be registered with
+    /// `ActiveCamera<Camera2d>`.
+    pub fn standard_2d() -> (Self, Camera2d) {
+        (
+            Self::from(Projection::orthographic(-1., 1., 1., -1., -1000., 1000.)),
+            Camera2d,
+        )
     }
 
     /// Create a standard camera for 3D.
@@ -81,20 +127,321 @@ impl Camera {
     /// Will use a perspective projection with aspect from the given screen dimensions and a field
     /// of view of 60 degrees.
     /// View transformation will be multiplicative identity.
-    pub fn standard_3d(width: f32, height: f32) -> Self {
+    ///
+    /// Also returns a `Camera3d` marker, so the camera can be registered with
+    /// `ActiveCamera<Camera3d>`.
+    pub fn standard_3d(width: f32, height: f32) -> (Self, Camera3d) {
         use amethyst_core::cgmath::Deg;
-        Self::from(Projection::perspective(width / height, Deg(60.)))
+        (
+            Self::from(Projection::perspective(width / height, Deg(60.))),
+            Camera3d,
+        )
     }
+
+    /// Converts a screen-space position (in pixels, origin at the top left of the viewport) into
+    /// a world-space position on the camera's near plane. `camera_transform` is the camera
+    /// entity's own world transform.
+    pub fn screen_to_world(
+        &self,
+        screen_point: Point2<f32>,
+        screen_dims: Vector2<f32>,
+        camera_transform: &Matrix4<f32>,
+    ) -> Point3<f32> {
+        let inverse_view_proj = self.inverse_view_proj(camera_transform);
+        let ndc = screen_to_ndc(screen_point, screen_dims, -1.0);
+        unproject(&inverse_view_proj, ndc)
+    }
+
+    /// Converts a world-space position into a screen-space position (in pixels, origin at the
+    /// top left of the viewport). The inverse of `screen_to_world`.
+    pub fn world_to_screen(
+        &self,
+        world_point: Point3<f32>,
+        screen_dims: Vector2<f32>,
+        camera_transform: &Matrix4<f32>,
+    ) -> Point2<f32> {
+        let view_proj = self.matrix * view_matrix(camera_transform);
+        let clip = view_proj * Vector4::new(world_point.x, world_point.y, world_point.z, 1.0);
+        let ndc = Point3::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w);
+
+        Point2::new(
+            (ndc.x + 1.0) / 2.0 * screen_dims.x,
+            (1.0 - ndc.y) / 2.0 * screen_dims.y,
+        )
+    }
+
+    /// Builds a ray from the camera through the given screen-space position, for use in mouse
+    /// picking against colliders. Returns a world-space origin on the near plane and a
+    /// normalized direction pointing into the scene.
+    pub fn screen_ray(
+        &self,
+        screen_point: Point2<f32>,
+        screen_dims: Vector2<f32>,
+        camera_transform: &Matrix4<f32>,
+    ) -> (Point3<f32>, Vector3<f32>) {
+        let inverse_view_proj = self.inverse_view_proj(camera_transform);
+
+        let near = unproject(
+            &inverse_view_proj,
+            screen_to_ndc(screen_point, screen_dims, -1.0),
+        );
+        let far = unproject(
+            &inverse_view_proj,
+            screen_to_ndc(screen_point, screen_dims, 1.0),
+        );
+
+        (near, (far - near).normalize())
+    }
+
+    fn inverse_view_proj(&self, camera_transform: &Matrix4<f32>) -> Matrix4<f32> {
+        (self.matrix * view_matrix(camera_transform))
+            .invert()
+            .expect("camera view-projection matrix is not invertible")
+    }
+
+    /// Extracts this camera's view frustum, combined with the given view transform. Used by the
+    /// renderer to cull entities whose bounding volume falls entirely outside the camera's view.
+    pub fn frustum(&self, view: &Matrix4<f32>) -> Frustum {
+        Frustum::from_matrix4(self.matrix * view)
+    }
+}
+
+/// Inverts a camera entity's world transform into a view matrix.
+fn view_matrix(camera_transform: &Matrix4<f32>) -> Matrix4<f32> {
+    camera_transform
+        .invert()
+        .expect("camera transform is not invertible")
+}
+
+/// Maps a screen-space pixel to normalized device coordinates at the given NDC depth.
+fn screen_to_ndc(screen_point: Point2<f32>, screen_dims: Vector2<f32>, z: f32) -> Point3<f32> {
+    Point3::new(
+        2.0 * screen_point.x / screen_dims.x - 1.0,
+        1.0 - 2.0 * screen_point.y / screen_dims.y,
+        z,
+    )
+}
+
+/// Transforms a normalized-device-coordinate point by the given inverse view-projection matrix,
+/// undoing the perspective divide.
+fn unproject(inverse_view_proj: &Matrix4<f32>, ndc: Point3<f32>) -> Point3<f32> {
+    let clip = inverse_view_proj * Vector4::new(ndc.x, ndc.y, ndc.z, 1.0);
+    Point3::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+}
+
+/// A view frustum, represented as six clipping planes extracted from a combined
+/// view-projection matrix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frustum {
+    /// The six clipping planes, in `left, right, bottom, top, near, far` order. Each plane is
+    /// stored as `(normal, distance)`, normalized so that a point `p` lies inside the plane when
+    /// `normal.dot(p) + distance >= 0`.
+    pub planes: [(Vector3<f32>, f32); 6],
+}
+
+impl Frustum {
+    /// Extracts a view frustum from the rows of a combined view-projection matrix.
+    pub fn from_matrix4(matrix: Matrix4<f32>) -> Self {
+        let row0 = Vector4::new(matrix.x.x, matrix.y.x, matrix.z.x, matrix.w.x);
+        let row1 = Vector4::new(matrix.x.y, matrix.y.y, matrix.z.y, matrix.w.y);
+        let row2 = Vector4::new(matrix.x.z, matrix.y.z, matrix.z.z, matrix.w.z);
+        let row3 = Vector4::new(matrix.x.w, matrix.y.w, matrix.z.w, matrix.w.w);
+
+        Frustum {
+            planes: [
+                normalize_plane(row3 + row0), // left
+                normalize_plane(row3 - row0), // right
+                normalize_plane(row3 + row1), // bottom
+                normalize_plane(row3 - row1), // top
+                normalize_plane(row3 + row2), // near
+                normalize_plane(row3 - row2), // far
+            ],
+        }
+    }
+
+    /// Returns `true` if the given sphere is at least partially inside every clipping plane.
+    pub fn intersects_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|(normal, distance)| normal.dot(center.to_vec()) + distance >= -radius)
+    }
+
+    /// Returns `true` if the given axis-aligned bounding box is at least partially inside every
+    /// clipping plane.
+    pub fn intersects_aabb(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|(normal, distance)| {
+            let positive = Point3::new(
+                if normal.x >= 0.0 { max.x } else { min.x },
+                if normal.y >= 0.0 { max.y } else { min.y },
+                if normal.z >= 0.0 { max.z } else { min.z },
+            );
+
+            normal.dot(positive.to_vec()) + distance >= 0.0
+        })
+    }
+}
+
+/// Normalizes a plane in `ax + by + cz + d = 0` form so its normal has unit length.
+fn normalize_plane(plane: Vector4<f32>) -> (Vector3<f32>, f32) {
+    let normal = Vector3::new(plane.x, plane.y, plane.z);
+    let magnitude = normal.magnitude();
+    (normal / magnitude, plane.w / magnitude)
 }
 
 impl Component for Camera {
     type Storage = HashMapStorage<Self>;
 }
 
-/// Active camera resource, used by the renderer to choose which camera to get the view matrix from.
-/// If no active camera is found, the first camera will be used as a fallback.
+/// Marker component for the entity holding the primary 2D camera. Returned alongside the camera
+/// by `Camera::standard_2d`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Camera2d;
+
+impl Component for Camera2d {
+    type Storage = NullStorage<Self>;
+}
+
+/// Marker component for the entity holding the primary 3D camera. Returned alongside the camera
+/// by `Camera::standard_3d`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Camera3d;
+
+impl Component for Camera3d {
+    type Storage = NullStorage<Self>;
+}
+
+/// Active camera resource, used by the renderer to choose which camera to get the view matrix
+/// from. Parameterized by a marker component `M` (e.g. `Camera2d`, `Camera3d`) so more than one
+/// can be active at once. If no `ActiveCamera<M>` is set, the first entity found with both a
+/// `Camera` and `M` is used as a fallback.
 #[derive(Clone, Debug, PartialEq)]
-pub struct ActiveCamera {
+pub struct ActiveCamera<M> {
     /// Camera entity
     pub entity: Entity,
+    marker: PhantomData<M>,
+}
+
+impl<M> ActiveCamera<M> {
+    /// Creates an `ActiveCamera` pointing at the given entity.
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Keeps every `Camera`'s projection matrix up to date with the window's `ScreenDimensions`.
+///
+/// Runs every frame rather than only on resize, since `proj` is a public field and may have been
+/// edited directly since the last tick.
+#[derive(Default)]
+pub struct CameraProjectionSystem;
+
+impl<'a> System<'a> for CameraProjectionSystem {
+    type SystemData = (
+        Fetch<'a, ScreenDimensions>,
+        WriteStorage<'a, Camera>,
+        ReadStorage<'a, FixedAspect>,
+    );
+
+    fn run(&mut self, (screen_dimensions, mut cameras, fixed_aspect): Self::SystemData) {
+        let (width, height) = (screen_dimensions.width(), screen_dimensions.height());
+
+        for (camera, _) in (&mut cameras, !&fixed_aspect).join() {
+            camera.proj.update(width, height);
+            camera.matrix = camera.proj.get_projection_matrix();
+        }
+
+        // `FixedAspect` cameras keep their own aspect, but still pick up direct edits to `proj`.
+        for (camera, _) in (&mut cameras, &fixed_aspect).join() {
+            camera.matrix = camera.proj.get_projection_matrix();
+        }
+    }
+}
+
+/// Marker component that opts a `Camera` out of `CameraProjectionSystem`'s automatic aspect
+/// tracking against the window's `ScreenDimensions`. Attach this to cameras that manage their own
+/// aspect ratio, such as one of several split-screen viewports, so they aren't reset back to the
+/// full window's aspect every frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FixedAspect;
+
+impl Component for FixedAspect {
+    type Storage = NullStorage<Self>;
+}
+
+/// A macroquad-style 2D camera rig: pans, zooms, and rotates the view produced by
+/// `Camera::standard_2d` without requiring callers to hand-edit `Ortho` planes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera2D {
+    /// World-space point the camera is centered on.
+    pub target: Point2<f32>,
+    /// Zoom factor along each axis. `1.0` leaves the `standard_2d` scale unchanged; larger values
+    /// zoom in.
+    pub zoom: Vector2<f32>,
+    /// Rotation of the view around `target`, in degrees.
+    pub rotation: f32,
+    /// Additional offset, in normalized device coordinates (`-1.0..1.0` across the viewport),
+    /// applied after projection so it stays fixed on screen regardless of `zoom`/`rotation` --
+    /// e.g. to keep the target off-center.
+    pub offset: Vector2<f32>,
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Camera2D {
+            target: Point2::new(0., 0.),
+            zoom: Vector2::new(1., 1.),
+            rotation: 0.,
+            offset: Vector2::new(0., 0.),
+        }
+    }
+}
+
+impl Camera2D {
+    /// Creates a `Camera2D` centered on `target`, with no zoom, rotation, or offset.
+    pub fn new(target: Point2<f32>) -> Self {
+        Camera2D {
+            target,
+            ..Default::default()
+        }
+    }
+
+    /// Composes `target`, `zoom`, `rotation`, and `offset` on top of `camera`'s own projection
+    /// matrix (typically a `Camera::standard_2d`, kept aspect-correct by
+    /// `CameraProjectionSystem`), so callers don't need to hand-edit `Ortho` planes to pan, zoom,
+    /// or rotate a 2D view, and still get the viewport's aspect-ratio correction for free.
+    pub fn matrix(&self, camera: &Camera) -> Matrix4<f32> {
+        let target_translation = Matrix4::from_translation((-self.target.to_vec()).extend(0.0));
+        let rotation = Matrix4::from_angle_z(Deg(self.rotation));
+        let scale = Matrix4::from_nonuniform_scale(self.zoom.x, self.zoom.y, 1.0);
+        // Applied after projection, so `offset` is a fixed normalized-device offset that doesn't
+        // itself get zoomed or rotated along with the view.
+        let offset_translation =
+            Matrix4::from_translation(Vector3::new(self.offset.x, self.offset.y, 0.0));
+
+        offset_translation * camera.matrix * scale * rotation * target_translation
+    }
+}
+
+impl Component for Camera2D {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Overlays each `Camera2D`'s pan/zoom/rotation onto the `Camera` it is attached to. Must be
+/// scheduled after `CameraProjectionSystem`, which would otherwise overwrite `Camera::matrix`
+/// with the bare, un-rigged projection on the same tick.
+#[derive(Default)]
+pub struct Camera2DProjectionSystem;
+
+impl<'a> System<'a> for Camera2DProjectionSystem {
+    type SystemData = (WriteStorage<'a, Camera>, ReadStorage<'a, Camera2D>);
+
+    fn run(&mut self, (mut cameras, camera_2ds): Self::SystemData) {
+        for (camera, camera_2d) in (&mut cameras, &camera_2ds).join() {
+            let matrix = camera_2d.matrix(&*camera);
+            camera.matrix = matrix;
+        }
+    }
 }